@@ -1,4 +1,5 @@
 use crate::prelude::Error;
+use winapi::shared::minwindef::FILETIME;
 use winreg::types::{FromRegValue, ToRegValue};
 
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
@@ -8,9 +9,26 @@ pub struct Hives(pub std::collections::HashMap<String, Keys>);
 pub struct Keys(pub std::collections::HashMap<String, Entries>);
 
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
-pub struct Entries(pub std::collections::HashMap<String, Entry>);
+pub struct Entries {
+    #[serde(flatten)]
+    pub entries: std::collections::HashMap<String, Entry>,
+    // Renamed away from a plain "metadata" so a registry value that happens to be named that
+    // doesn't collide with this field in the flattened map above.
+    #[serde(rename = "__ludusaviKeyMetadata", skip_serializing_if = "Option::is_none", default)]
+    pub metadata: Option<KeyMetadata>,
+}
 
-#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+/// Metadata about a backed-up registry key, stored alongside its `Entries` so backups carry
+/// provenance without affecting the entry values themselves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct KeyMetadata {
+    /// The key's last-write time, as a Windows `FILETIME` (100-ns intervals since 1601-01-01),
+    /// packed into a single `u64`.
+    #[serde(rename = "lastWriteTime")]
+    pub last_write_time: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Entry {
     sz: Option<String>,
     #[serde(rename = "expandSz")]
@@ -19,12 +37,82 @@ pub struct Entry {
     multi_sz: Option<String>,
     dword: Option<u32>,
     qword: Option<u64>,
+    /// Hex-encoded raw bytes of a `REG_BINARY` value.
+    binary: Option<String>,
+    /// Hex-encoded raw bytes of a `REG_NONE` value.
+    none: Option<String>,
+    #[serde(rename = "dwordBigEndian")]
+    dword_big_endian: Option<u32>,
 }
 
 pub struct RegistryInfo {
     pub found: bool,
 }
 
+/// The result of comparing a backup's stored hives/keys/entries against the live registry,
+/// keyed the same way as `Hives` itself.
+#[derive(Debug, Default)]
+pub struct RegistryDiff {
+    pub entries: std::collections::HashMap<String, std::collections::HashMap<String, std::collections::HashMap<String, EntryDiff>>>,
+    /// Keys (by hive name, key name) that have no live subkey at all; restoring would create the
+    /// whole key, not just overwrite a value inside it. Tracked separately from `entries` so
+    /// `summarize` can report "create N keys" distinctly from "add/change N values".
+    pub missing_keys: std::collections::HashSet<(String, String)>,
+    /// Keys (by hive name, key name) whose live last-write time is newer than what's recorded in
+    /// the backup's `KeyMetadata`, i.e. the system has changed since the backup was taken.
+    pub stale_keys: std::collections::HashSet<(String, String)>,
+}
+
+/// How a single stored entry compares to what's currently in the registry.
+#[derive(Debug, PartialEq)]
+pub enum EntryDiff {
+    /// The key or value isn't present on the live system; restoring would create it.
+    Missing,
+    /// The live value already matches what's stored.
+    Same,
+    /// The live value differs from what's stored; restoring would overwrite it.
+    Different { live: Entry, backup: Entry },
+}
+
+/// Tally of how many keys/values fall into each bucket, for a quick restore preview (e.g. "this
+/// restore will change 3 registry values, create 2 keys").
+#[derive(Debug, Default, PartialEq)]
+pub struct RegistryDiffSummary {
+    /// Keys that don't exist on the live system at all and would be created.
+    pub keys_missing: usize,
+    /// Values missing inside a key that does already exist on the live system.
+    pub values_missing: usize,
+    pub values_same: usize,
+    pub values_different: usize,
+}
+
+impl RegistryDiff {
+    pub fn summarize(&self) -> RegistryDiffSummary {
+        let mut summary = RegistryDiffSummary {
+            keys_missing: self.missing_keys.len(),
+            ..Default::default()
+        };
+
+        for (hive_name, keys) in &self.entries {
+            for (key_name, entries) in keys {
+                let key_missing = self.missing_keys.contains(&(hive_name.clone(), key_name.clone()));
+                for diff in entries.values() {
+                    match diff {
+                        // Already counted once via `missing_keys` above, rather than once per
+                        // value, when the whole key is absent.
+                        EntryDiff::Missing if key_missing => {}
+                        EntryDiff::Missing => summary.values_missing += 1,
+                        EntryDiff::Same => summary.values_same += 1,
+                        EntryDiff::Different { .. } => summary.values_different += 1,
+                    }
+                }
+            }
+        }
+
+        summary
+    }
+}
+
 impl Hives {
     pub fn load(file: &std::path::PathBuf) -> Option<Self> {
         if crate::path::is_file(&crate::path::render_pathbuf(&file)) {
@@ -76,12 +164,26 @@ impl Hives {
                     .0
                     .entry(key.to_string())
                     .or_insert_with(Default::default)
-                    .0
+                    .entries
                     .entry(name.to_string())
                     .or_insert_with(|| entry);
             }
         }
 
+        if info.found {
+            if let Ok(key_info) = subkey.query_info() {
+                self.0
+                    .entry(hive_name.to_string())
+                    .or_insert_with(Default::default)
+                    .0
+                    .entry(key.to_string())
+                    .or_insert_with(Default::default)
+                    .metadata = Some(KeyMetadata {
+                    last_write_time: filetime_to_u64(key_info.last_write_time),
+                });
+            }
+        }
+
         let mut failed = false;
         for name in subkey.enum_keys().filter_map(|x| x.ok()) {
             if self.store_key(hive, hive_name, &format!("{}\\{}", key, name)).is_err() {
@@ -96,7 +198,55 @@ impl Hives {
         Ok(info)
     }
 
+    /// Restore every stored hive/key/entry, using a registry transaction so that a partial
+    /// failure rolls back cleanly rather than leaving the registry half-written. Falls back to
+    /// `restore_without_transaction` only when `winreg::transaction::Transaction::new` itself
+    /// fails, since that happens before any writes are attempted and so is still safe to retry
+    /// non-transactionally. Any later failure (a bad entry, a permission error, a missing hive
+    /// mapping, ...) is returned as-is rather than retried, since by the time it surfaces the
+    /// transaction has already rolled back and retrying without one would write the remaining
+    /// good entries and leave the registry half-written anyway.
     pub fn restore(&self) -> Result<(), Error> {
+        match winreg::transaction::Transaction::new() {
+            Ok(transaction) => self.restore_with_transaction(&transaction),
+            Err(_) => self.restore_without_transaction(),
+        }
+    }
+
+    /// Restore via a single `winreg::transaction::Transaction`, committing only once every
+    /// hive/key/entry has been written successfully. Dropping the transaction without committing
+    /// implicitly rolls back any writes made so far.
+    pub fn restore_transacted(&self) -> Result<(), Error> {
+        let transaction = winreg::transaction::Transaction::new().map_err(|_| Error::RegistryIssue)?;
+        self.restore_with_transaction(&transaction)
+    }
+
+    fn restore_with_transaction(&self, transaction: &winreg::transaction::Transaction) -> Result<(), Error> {
+        for (hive_name, keys) in self.0.iter() {
+            let hive = winreg::RegKey::predef(get_hkey_from_name(hive_name).ok_or(Error::RegistryIssue)?);
+
+            for (key_name, entries) in keys.0.iter() {
+                let (key, _) = hive
+                    .create_subkey_transacted(key_name, transaction)
+                    .map_err(|_| Error::RegistryIssue)?;
+
+                for (entry_name, entry) in entries.entries.iter() {
+                    let value = Option::<winreg::RegValue>::from(entry).ok_or(Error::RegistryIssue)?;
+                    key.set_raw_value(entry_name, &value).map_err(|_| Error::RegistryIssue)?;
+                }
+            }
+        }
+
+        transaction.commit().map_err(|_| Error::RegistryIssue)?;
+
+        Ok(())
+    }
+
+    /// Non-transactional restore. `restore` already falls back to this automatically when
+    /// transacted registry APIs are unavailable; call it directly to force non-transactional
+    /// restore on a system that does support them. On any error, writes already made to the
+    /// registry are left in place.
+    pub fn restore_without_transaction(&self) -> Result<(), Error> {
         let mut failed = false;
 
         for (hive_name, keys) in self.0.iter() {
@@ -117,7 +267,7 @@ impl Hives {
                     }
                 };
 
-                for (entry_name, entry) in entries.0.iter() {
+                for (entry_name, entry) in entries.entries.iter() {
                     if let Some(value) = Option::<winreg::RegValue>::from(entry) {
                         if key.set_raw_value(entry_name, &value).is_err() {
                             failed = true;
@@ -135,6 +285,65 @@ impl Hives {
 
         Ok(())
     }
+
+    /// Compare every stored hive/key/entry against the live registry, without writing anything,
+    /// so callers can preview what a restore would do (e.g. "this will change 3 values, create
+    /// 2 keys").
+    pub fn diff_against_system(&self) -> RegistryDiff {
+        let mut diff = RegistryDiff::default();
+
+        for (hive_name, keys) in self.0.iter() {
+            let hive = match get_hkey_from_name(hive_name) {
+                Some(x) => winreg::RegKey::predef(x),
+                None => continue,
+            };
+
+            for (key_name, entries) in keys.0.iter() {
+                let subkey = hive.open_subkey(key_name).ok();
+
+                if subkey.is_none() && !entries.entries.is_empty() {
+                    diff.missing_keys.insert((hive_name.clone(), key_name.clone()));
+                }
+
+                if let (Some(subkey), Some(metadata)) = (&subkey, entries.metadata) {
+                    if let Ok(key_info) = subkey.query_info() {
+                        if filetime_to_u64(key_info.last_write_time) > metadata.last_write_time {
+                            diff.stale_keys.insert((hive_name.clone(), key_name.clone()));
+                        }
+                    }
+                }
+
+                for (entry_name, entry) in entries.entries.iter() {
+                    let entry_diff = match &subkey {
+                        None => EntryDiff::Missing,
+                        Some(subkey) => match subkey.get_raw_value(entry_name) {
+                            Err(_) => EntryDiff::Missing,
+                            Ok(raw) => {
+                                let live = Entry::from(raw);
+                                if &live == entry {
+                                    EntryDiff::Same
+                                } else {
+                                    EntryDiff::Different {
+                                        live,
+                                        backup: entry.clone(),
+                                    }
+                                }
+                            }
+                        },
+                    };
+
+                    diff.entries
+                        .entry(hive_name.clone())
+                        .or_default()
+                        .entry(key_name.clone())
+                        .or_default()
+                        .insert(entry_name.clone(), entry_diff);
+                }
+            }
+        }
+
+        diff
+    }
 }
 
 impl Entry {
@@ -144,6 +353,9 @@ impl Entry {
             || self.multi_sz.is_some()
             || self.dword.is_some()
             || self.qword.is_some()
+            || self.binary.is_some()
+            || self.none.is_some()
+            || self.dword_big_endian.is_some()
     }
 }
 
@@ -170,6 +382,25 @@ impl From<winreg::RegValue> for Entry {
                 qword: Some(u64::from_reg_value(&item).unwrap_or_default()),
                 ..Default::default()
             },
+            winreg::enums::RegType::REG_BINARY => Self {
+                binary: Some(bytes_to_hex(&item.bytes)),
+                ..Default::default()
+            },
+            winreg::enums::RegType::REG_NONE => Self {
+                none: Some(bytes_to_hex(&item.bytes)),
+                ..Default::default()
+            },
+            winreg::enums::RegType::REG_DWORD_BIG_ENDIAN if item.bytes.len() == 4 => {
+                let mut buf = [0u8; 4];
+                buf.copy_from_slice(&item.bytes);
+                Self {
+                    dword_big_endian: Some(u32::from_be_bytes(buf)),
+                    ..Default::default()
+                }
+            }
+            // A `REG_DWORD_BIG_ENDIAN` value is always exactly 4 bytes; anything else is
+            // malformed, so fall back to the same silent default as other unsupported types
+            // rather than truncating and losing data without any way to detect it.
             _ => Default::default(),
         }
     }
@@ -187,16 +418,56 @@ impl From<&Entry> for Option<winreg::RegValue> {
             Some(x.to_reg_value())
         } else if let Some(x) = &item.qword {
             Some(x.to_reg_value())
+        } else if let Some(x) = &item.binary {
+            // `REG_BINARY` has no primitive Rust equivalent, so `ToRegValue` can't help here;
+            // rebuild the raw value directly from the decoded bytes.
+            hex_to_bytes(x).map(|bytes| winreg::RegValue {
+                bytes,
+                vtype: winreg::enums::RegType::REG_BINARY,
+            })
+        } else if let Some(x) = &item.none {
+            hex_to_bytes(x).map(|bytes| winreg::RegValue {
+                bytes,
+                vtype: winreg::enums::RegType::REG_NONE,
+            })
+        } else if let Some(x) = &item.dword_big_endian {
+            Some(winreg::RegValue {
+                bytes: x.to_be_bytes().to_vec(),
+                vtype: winreg::enums::RegType::REG_DWORD_BIG_ENDIAN,
+            })
         } else {
             None
         }
     }
 }
 
+fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_to_bytes(hex: &str) -> Option<Vec<u8>> {
+    // Reject non-ASCII input before slicing by byte offset below, since a multi-byte UTF-8
+    // character could otherwise pass the even-length check yet land a slice boundary mid-character.
+    if !hex.is_ascii() || hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn filetime_to_u64(filetime: FILETIME) -> u64 {
+    ((filetime.dwHighDateTime as u64) << 32) | filetime.dwLowDateTime as u64
+}
+
 fn get_hkey_from_name(name: &str) -> Option<winreg::HKEY> {
     match name {
-        "HKEY_CURRENT_USER" => Some(winreg::enums::HKEY_CURRENT_USER),
-        "HKEY_LOCAL_MACHINE" => Some(winreg::enums::HKEY_LOCAL_MACHINE),
+        "HKEY_CURRENT_USER" | "HKCU" => Some(winreg::enums::HKEY_CURRENT_USER),
+        "HKEY_LOCAL_MACHINE" | "HKLM" => Some(winreg::enums::HKEY_LOCAL_MACHINE),
+        "HKEY_CLASSES_ROOT" | "HKCR" => Some(winreg::enums::HKEY_CLASSES_ROOT),
+        "HKEY_USERS" | "HKU" => Some(winreg::enums::HKEY_USERS),
+        "HKEY_CURRENT_CONFIG" | "HKCC" => Some(winreg::enums::HKEY_CURRENT_CONFIG),
         _ => None,
     }
 }
@@ -207,3 +478,142 @@ pub fn game_registry_backup_file(start: &str, game: &str) -> std::path::PathBuf
     path.push("registry.yaml");
     path
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_counts_missing_keys_separately_from_missing_values() {
+        let mut diff = RegistryDiff::default();
+        diff.missing_keys
+            .insert(("HKEY_CURRENT_USER".to_string(), "Software\\MissingKey".to_string()));
+
+        // A value inside a key that's entirely missing should only be reflected in
+        // `keys_missing`, not also counted as a missing value.
+        diff.entries
+            .entry("HKEY_CURRENT_USER".to_string())
+            .or_default()
+            .entry("Software\\MissingKey".to_string())
+            .or_default()
+            .insert("SomeValue".to_string(), EntryDiff::Missing);
+
+        // A key that does exist, but with one value missing, one unchanged, and one changed.
+        let existing_key = diff
+            .entries
+            .entry("HKEY_CURRENT_USER".to_string())
+            .or_default()
+            .entry("Software\\ExistingKey".to_string())
+            .or_default();
+        existing_key.insert("Unset".to_string(), EntryDiff::Missing);
+        existing_key.insert("Unchanged".to_string(), EntryDiff::Same);
+        existing_key.insert(
+            "Changed".to_string(),
+            EntryDiff::Different {
+                live: Entry::default(),
+                backup: Entry::default(),
+            },
+        );
+
+        let summary = diff.summarize();
+        assert_eq!(
+            summary,
+            RegistryDiffSummary {
+                keys_missing: 1,
+                values_missing: 1,
+                values_same: 1,
+                values_different: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn entries_without_metadata_deserialize_from_old_yaml() {
+        let yaml = "MyValue:\n  sz: hello\n";
+        let entries: Entries = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(entries.entries["MyValue"].sz.as_deref(), Some("hello"));
+        assert_eq!(entries.metadata, None);
+    }
+
+    #[test]
+    fn filetime_round_trips_through_u64() {
+        let filetime = FILETIME {
+            dwLowDateTime: 0x1234_5678,
+            dwHighDateTime: 0x9abc_def0,
+        };
+        assert_eq!(filetime_to_u64(filetime), 0x9abc_def0_1234_5678);
+    }
+
+    #[test]
+    fn hex_to_bytes_rejects_non_ascii_instead_of_panicking() {
+        assert_eq!(hex_to_bytes("€0"), None);
+    }
+
+    #[test]
+    fn reg_binary_round_trips_through_entry() {
+        let original = winreg::RegValue {
+            bytes: vec![0xde, 0xad, 0xbe, 0xef],
+            vtype: winreg::enums::RegType::REG_BINARY,
+        };
+        let entry = Entry::from(original.clone());
+        assert_eq!(entry.binary.as_deref(), Some("deadbeef"));
+
+        let restored = Option::<winreg::RegValue>::from(&entry).unwrap();
+        assert_eq!(restored.vtype, winreg::enums::RegType::REG_BINARY);
+        assert_eq!(restored.bytes, original.bytes);
+    }
+
+    #[test]
+    fn reg_none_round_trips_through_entry() {
+        let original = winreg::RegValue {
+            bytes: vec![0x01, 0x02],
+            vtype: winreg::enums::RegType::REG_NONE,
+        };
+        let entry = Entry::from(original.clone());
+        assert_eq!(entry.none.as_deref(), Some("0102"));
+
+        let restored = Option::<winreg::RegValue>::from(&entry).unwrap();
+        assert_eq!(restored.vtype, winreg::enums::RegType::REG_NONE);
+        assert_eq!(restored.bytes, original.bytes);
+    }
+
+    #[test]
+    fn get_hkey_from_name_accepts_full_names_and_abbreviations() {
+        assert!(get_hkey_from_name("HKEY_CURRENT_USER").is_some());
+        assert!(get_hkey_from_name("HKCU").is_some());
+        assert!(get_hkey_from_name("HKEY_LOCAL_MACHINE").is_some());
+        assert!(get_hkey_from_name("HKLM").is_some());
+        assert!(get_hkey_from_name("HKEY_CLASSES_ROOT").is_some());
+        assert!(get_hkey_from_name("HKCR").is_some());
+        assert!(get_hkey_from_name("HKEY_USERS").is_some());
+        assert!(get_hkey_from_name("HKU").is_some());
+        assert!(get_hkey_from_name("HKEY_CURRENT_CONFIG").is_some());
+        assert!(get_hkey_from_name("HKCC").is_some());
+        assert!(get_hkey_from_name("NOT_A_HIVE").is_none());
+    }
+
+    #[test]
+    fn reg_dword_big_endian_round_trips_through_entry() {
+        let original = winreg::RegValue {
+            bytes: 0x0102_0304u32.to_be_bytes().to_vec(),
+            vtype: winreg::enums::RegType::REG_DWORD_BIG_ENDIAN,
+        };
+        let entry = Entry::from(original.clone());
+        assert_eq!(entry.dword_big_endian, Some(0x0102_0304));
+
+        let restored = Option::<winreg::RegValue>::from(&entry).unwrap();
+        assert_eq!(restored.vtype, winreg::enums::RegType::REG_DWORD_BIG_ENDIAN);
+        assert_eq!(restored.bytes, original.bytes);
+    }
+
+    #[test]
+    fn malformed_reg_dword_big_endian_is_not_truncated() {
+        let malformed = winreg::RegValue {
+            bytes: vec![0x01, 0x02, 0x03, 0x04, 0x05],
+            vtype: winreg::enums::RegType::REG_DWORD_BIG_ENDIAN,
+        };
+        let entry = Entry::from(malformed);
+        assert_eq!(entry.dword_big_endian, None);
+        assert!(!entry.is_set());
+    }
+}